@@ -1,35 +1,92 @@
 use rodio::Source;
 use std::f32::consts::PI;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 
-/// Generate Sine Wave
-pub struct SineWave {
-    freq: f32,
+/// XO-CHIP pattern state, written each frame from `Chip8::pattern_buffer`/`pitch` and
+/// read from the audio mixer thread.
+#[derive(Default)]
+pub struct PatternState {
+    pub pattern: [u8; 16],
+    pub pitch: u8,
+    pub loaded: bool,
+    /// Silences output while true. The sink is kept playing continuously (rather than
+    /// paused when `sound_timer` is 0) so `samples_played` keeps advancing at a steady
+    /// real audio rate for the emulator's main-loop timing to read.
+    pub muted: bool,
+}
+
+/// Fixed-point scale for `PatternSource::pattern_phase`: a Q32.32 position within the
+/// 128-bit pattern, so the fractional playback rate accumulates exactly instead of
+/// through a float timestamp that loses precision the longer it runs.
+const PHASE_SCALE: u64 = 1 << 32;
+
+/// Plays the XO-CHIP 128-bit pattern buffer back as a looping +/-amplitude waveform at
+/// `4000 * 2^((pitch - 64) / 48)` Hz. Falls back to a plain 220 Hz tone until a ROM has
+/// loaded a pattern with `F002`.
+pub struct PatternSource {
+    state: Arc<Mutex<PatternState>>,
     sample_rate: u32,
-    current_sample: u64,
+    /// Q32.32 fixed-point position within the pattern, wrapped mod `128 * PHASE_SCALE`
+    /// every sample so it never grows large enough to need an imprecise float.
+    pattern_phase: u64,
+    /// Sample index within one cycle of the 220 Hz fallback tone, wrapped mod
+    /// `sample_rate` for the same reason.
+    sine_phase: u32,
+    /// Total samples this source has produced, shared with the main loop so CPU/timer
+    /// stepping can be driven off samples the mixer thread has actually consumed
+    /// instead of Bevy's own wall-clock.
+    samples_played: Arc<AtomicU64>,
 }
 
-impl SineWave {
-    pub fn new(freq: f32) -> Self {
+impl PatternSource {
+    pub fn new(state: Arc<Mutex<PatternState>>, samples_played: Arc<AtomicU64>) -> Self {
         Self {
-            freq,
+            state,
             sample_rate: 44100,
-            current_sample: 0,
+            pattern_phase: 0,
+            sine_phase: 0,
+            samples_played,
         }
     }
 }
 
-impl Iterator for SineWave {
+impl Iterator for PatternSource {
     type Item = f32;
 
     fn next(&mut self) -> Option<f32> {
-        let time = self.current_sample as f32 / self.sample_rate as f32;
-        let value = (2.0 * PI * self.freq * time).sin();
-        self.current_sample = self.current_sample.wrapping_add(1);
+        let state = self.state.lock().unwrap();
+        self.samples_played.fetch_add(1, Ordering::Relaxed);
+
+        if state.muted {
+            return Some(0.0);
+        }
+
+        let value = if state.loaded {
+            let playback_rate = 4000.0 * 2f64.powf((state.pitch as f64 - 64.0) / 48.0);
+            let step = ((playback_rate / self.sample_rate as f64) * PHASE_SCALE as f64) as u64;
+            let bit_index = (self.pattern_phase / PHASE_SCALE) as usize % 128;
+            let byte = state.pattern[bit_index / 8];
+            let bit = (byte >> (7 - (bit_index % 8))) & 1;
+
+            self.pattern_phase = (self.pattern_phase + step) % (128 * PHASE_SCALE);
+
+            if bit == 1 {
+                1.0
+            } else {
+                -1.0
+            }
+        } else {
+            let time = self.sine_phase as f32 / self.sample_rate as f32;
+            self.sine_phase = (self.sine_phase + 1) % self.sample_rate;
+            (2.0 * PI * 220.0 * time).sin()
+        };
+
         Some(value)
     }
 }
 
-impl Source for SineWave {
+impl Source for PatternSource {
     fn current_frame_len(&self) -> Option<usize> {
         None
     }
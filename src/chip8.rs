@@ -1,7 +1,65 @@
 use bevy::ecs::system::Resource;
+use serde::{Deserialize, Serialize};
 use std::{path::PathBuf, usize};
 
-#[derive(Resource)]
+/// Per-instruction behavior that differs between the original COSMAC VIP interpreter
+/// and the later SUPER-CHIP/XO-CHIP extensions. Ambiguous opcodes read this config
+/// instead of hard-coding one interpreter's behavior, so ROMs written for a specific
+/// platform actually run correctly.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Quirks {
+    /// `8XY6`/`8XYE`: shift VY into VX (true) instead of shifting VX in place (false).
+    pub shift_uses_vy: bool,
+    /// `BXNN`: jump to VX + NNN (true) instead of the classic `BNNN` V0 + NNN (false).
+    pub jump_with_vx: bool,
+    /// `FX55`/`FX65`: increment `i_register` by `x + 1` after the memory transfer.
+    pub save_load_increments_i: bool,
+}
+
+impl Default for Quirks {
+    /// Matches this emulator's pre-existing hard-coded behavior, so running without
+    /// `--compat` keeps working exactly as before.
+    fn default() -> Self {
+        Self {
+            shift_uses_vy: false,
+            jump_with_vx: false,
+            save_load_increments_i: false,
+        }
+    }
+}
+
+/// Named compatibility profiles covering the quirks of the three most common
+/// CHIP-8-family platforms, selectable with `--compat vip|schip|xochip`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum CompatProfile {
+    Vip,
+    Schip,
+    Xochip,
+}
+
+impl From<CompatProfile> for Quirks {
+    fn from(profile: CompatProfile) -> Self {
+        match profile {
+            CompatProfile::Vip => Quirks {
+                shift_uses_vy: true,
+                jump_with_vx: false,
+                save_load_increments_i: true,
+            },
+            CompatProfile::Schip => Quirks {
+                shift_uses_vy: false,
+                jump_with_vx: true,
+                save_load_increments_i: false,
+            },
+            CompatProfile::Xochip => Quirks {
+                shift_uses_vy: true,
+                jump_with_vx: false,
+                save_load_increments_i: false,
+            },
+        }
+    }
+}
+
+#[derive(Resource, Serialize, Deserialize)]
 pub struct Chip8 {
     pub memory: [u8; 4096],
     pub registers: [u8; 16],
@@ -10,9 +68,23 @@ pub struct Chip8 {
     pub delay_timer: u8,
     pub sound_timer: u8,
     pub stack: Vec<u16>,
-    pub display: [[u8; 64]; 32],
+    /// Always sized for SUPER-CHIP's 128x64 hi-res mode; in lo-res mode only the
+    /// top-left 64x32 region is used, and rendering upscales it 2x.
+    pub display: [[u8; 128]; 64],
     pub keys: [bool; 16],
     pub waiting_key_opcode: u16,
+    pub quirks: Quirks,
+    /// XO-CHIP 128-bit audio pattern buffer, set by `F002`.
+    pub pattern_buffer: [u8; 16],
+    /// XO-CHIP playback pitch register, set by `FX3A`. 64 plays the pattern at 4000 Hz.
+    pub pitch: u8,
+    /// Whether `F002` has ever loaded a pattern; while false the beeper falls back to
+    /// a plain tone instead of the (still silent) pattern buffer.
+    pub pattern_loaded: bool,
+    /// SUPER-CHIP 128x64 hi-res mode, toggled by `00FF`/`00FE`.
+    pub hires: bool,
+    /// SUPER-CHIP RPL user flags, persisted by `FX75`/`FX85`.
+    pub rpl_flags: [u8; 8],
 }
 
 impl Default for Chip8 {
@@ -25,9 +97,15 @@ impl Default for Chip8 {
             delay_timer: 0,
             sound_timer: 0,
             stack: Vec::new(),
-            display: [[0; 64]; 32],
+            display: [[0; 128]; 64],
             keys: [false; 16],
             waiting_key_opcode: 0,
+            quirks: Quirks::default(),
+            pattern_buffer: [0; 16],
+            pitch: 64,
+            pattern_loaded: false,
+            hires: false,
+            rpl_flags: [0; 8],
         }
     }
 }
@@ -48,11 +126,13 @@ impl Chip8 {
         self.i_register = 0;
         self.pc = 0x200;
         self.stack.clear();
-        self.display.fill([0; 64]);
+        self.clear_display();
         self.keys.fill(false);
         self.delay_timer = 0;
         self.sound_timer = 0;
         self.waiting_key_opcode = 0;
+        self.hires = false;
+        self.rpl_flags.fill(0);
     }
 
     pub fn restart(&mut self) {
@@ -60,11 +140,64 @@ impl Chip8 {
         self.i_register = 0;
         self.pc = 0x200;
         self.stack.clear();
-        self.display.fill([0; 64]);
+        self.clear_display();
         self.keys.fill(false);
         self.delay_timer = 0;
         self.sound_timer = 0;
         self.waiting_key_opcode = 0;
+        self.hires = false;
+        self.rpl_flags.fill(0);
+    }
+
+    /// Width of the active display mode: 128 in hi-res, 64 in lo-res.
+    pub fn display_width(&self) -> usize {
+        if self.hires {
+            128
+        } else {
+            64
+        }
+    }
+
+    /// Height of the active display mode: 64 in hi-res, 32 in lo-res.
+    pub fn display_height(&self) -> usize {
+        if self.hires {
+            64
+        } else {
+            32
+        }
+    }
+
+    fn clear_display(&mut self) {
+        for row in self.display.iter_mut() {
+            row.fill(0);
+        }
+    }
+
+    fn scroll_down(&mut self, n: usize) {
+        let (width, height) = (self.display_width(), self.display_height());
+        for y in (0..height).rev() {
+            for x in 0..width {
+                self.display[y][x] = if y >= n { self.display[y - n][x] } else { 0 };
+            }
+        }
+    }
+
+    fn scroll_left(&mut self, n: usize) {
+        let (width, height) = (self.display_width(), self.display_height());
+        for y in 0..height {
+            for x in 0..width {
+                self.display[y][x] = if x + n < width { self.display[y][x + n] } else { 0 };
+            }
+        }
+    }
+
+    fn scroll_right(&mut self, n: usize) {
+        let (width, height) = (self.display_width(), self.display_height());
+        for y in 0..height {
+            for x in (0..width).rev() {
+                self.display[y][x] = if x >= n { self.display[y][x - n] } else { 0 };
+            }
+        }
     }
 
     fn load_rom(&mut self, rom: &[u8], start_address: usize) {
@@ -83,6 +216,40 @@ impl Chip8 {
         self.load_rom(&buffer, 0x200);
     }
 
+    /// Serialize the full machine state (memory, registers, timers, stack, display,
+    /// keys and the pending key-wait opcode) to a byte blob suitable for a save file
+    /// or an in-memory rewind snapshot.
+    pub fn save_state(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("failed to serialize Chip8 state")
+    }
+
+    /// Restore a machine state previously produced by [`Chip8::save_state`].
+    pub fn load_state(&mut self, data: &[u8]) {
+        *self = bincode::deserialize(data).expect("failed to deserialize Chip8 state");
+    }
+
+    pub fn save_state_to_file(&self, filename: &PathBuf) {
+        std::fs::write(filename, self.save_state()).unwrap();
+    }
+
+    /// Restore a state previously written by [`Chip8::save_state_to_file`]. Unlike
+    /// loading a ROM at startup, this is a runtime hotkey the player may press
+    /// speculatively (e.g. before any save exists), so a missing or corrupt file just
+    /// logs and leaves the running machine untouched instead of panicking.
+    pub fn load_state_from_file(&mut self, filename: &PathBuf) {
+        let data = match std::fs::read(filename) {
+            Ok(data) => data,
+            Err(err) => {
+                eprintln!("No save state to load at {:?}: {}", filename, err);
+                return;
+            }
+        };
+        match bincode::deserialize(&data) {
+            Ok(state) => *self = state,
+            Err(err) => eprintln!("Save state at {:?} is corrupt: {}", filename, err),
+        }
+    }
+
     pub fn get_current_opcode(&self) -> u16 {
         let byte1 = self.memory[self.pc as usize] as u16;
         let byte2 = self.memory[(self.pc + 1) as usize] as u16;
@@ -127,8 +294,19 @@ impl Chip8 {
 
     fn handle_0xxx(&mut self, opcode: u16) {
         match opcode {
-            0x00E0 => self.display.fill([0; 64]),
+            0x00E0 => self.clear_display(),
             0x00EE => self.pc = self.stack.pop().expect("Stack underflow"),
+            0x00FE => {
+                self.hires = false;
+                self.clear_display();
+            }
+            0x00FF => {
+                self.hires = true;
+                self.clear_display();
+            }
+            0x00FB => self.scroll_right(4),
+            0x00FC => self.scroll_left(4),
+            _ if opcode & 0xFFF0 == 0x00C0 => self.scroll_down((opcode & 0x000F) as usize),
             _ => panic!("Unknown opcode: {:#X}", opcode),
         }
     }
@@ -207,8 +385,9 @@ impl Chip8 {
                 self.registers[x] = vx.wrapping_sub(vy);
             }
             0x8006 => {
-                self.registers[0xF] = vx & 0x01;
-                self.registers[x] = vx >> 1;
+                let src = if self.quirks.shift_uses_vy { vy } else { vx };
+                self.registers[0xF] = src & 0x01;
+                self.registers[x] = src >> 1;
             }
             0x8007 => {
                 let (res, carry) = vy.overflowing_sub(vx);
@@ -216,8 +395,9 @@ impl Chip8 {
                 self.registers[x] = res;
             }
             0x800E => {
-                self.registers[0xF] = (vx >> 7) & 1;
-                self.registers[x] = vx << 1;
+                let src = if self.quirks.shift_uses_vy { vy } else { vx };
+                self.registers[0xF] = (src >> 7) & 1;
+                self.registers[x] = src << 1;
             }
             _ => panic!("Unknown opcode: {:#X}", opcode),
         }
@@ -236,7 +416,12 @@ impl Chip8 {
     }
 
     fn handle_Bxxx(&mut self, opcode: u16) {
-        self.pc = self.registers[0] as u16 + (opcode & 0xFFF);
+        if self.quirks.jump_with_vx {
+            let x = ((opcode & 0x0F00) >> 8) as usize;
+            self.pc = self.registers[x] as u16 + (opcode & 0x0FFF);
+        } else {
+            self.pc = self.registers[0] as u16 + (opcode & 0xFFF);
+        }
     }
 
     fn handle_Cxxx(&mut self, opcode: u16) {
@@ -246,19 +431,27 @@ impl Chip8 {
     }
 
     fn handle_Dxxx(&mut self, opcode: u16) {
+        let (width, height) = (self.display_width(), self.display_height());
         let vx = self.registers[((opcode & 0xF00) >> 8) as usize];
         let vy = self.registers[((opcode & 0x0F0) >> 4) as usize];
         let n = opcode & 0x000F;
-        let x = (vx as usize) % 64;
-        let y = (vy as usize) % 32;
-        let sprite_data =
-            &self.memory[self.i_register as usize..self.i_register as usize + n as usize];
+        let x = (vx as usize) % width;
+        let y = (vy as usize) % height;
         self.registers[0xF] = 0;
-        for row in 0..n {
-            for col in 0..8 {
-                let pixel = (sprite_data[row as usize] >> (7 - col)) & 1;
-                let disp_x = (x + col as usize) % 64;
-                let disp_y = (y + row as usize) % 32;
+
+        // N == 0 draws a 16x16 sprite: two bytes per row, 16 rows.
+        let (rows, cols) = if n == 0 { (16, 16) } else { (n as usize, 8) };
+        for row in 0..rows {
+            let sprite_row: u16 = if cols == 16 {
+                let addr = self.i_register as usize + row * 2;
+                ((self.memory[addr] as u16) << 8) | self.memory[addr + 1] as u16
+            } else {
+                self.memory[self.i_register as usize + row] as u16
+            };
+            for col in 0..cols {
+                let pixel = ((sprite_row >> (cols - 1 - col)) & 1) as u8;
+                let disp_x = (x + col) % width;
+                let disp_y = (y + row) % height;
                 let cur_pixel = self.display[disp_y][disp_x];
                 if cur_pixel > 0 && pixel > 0 {
                     self.registers[0xF] = 1;
@@ -290,6 +483,11 @@ impl Chip8 {
         let x = ((opcode & 0xF00) >> 8) as usize;
         let vx = self.registers[x];
         match opcode & 0xF0FF {
+            0xF002 => {
+                let start = self.i_register as usize;
+                self.pattern_buffer.copy_from_slice(&self.memory[start..start + 16]);
+                self.pattern_loaded = true;
+            }
             0xF007 => {
                 self.registers[x] = self.delay_timer;
             }
@@ -315,6 +513,9 @@ impl Chip8 {
             0xF029 => {
                 self.i_register = self.registers[x] as u16 * 0x05;
             }
+            0xF03A => {
+                self.pitch = vx;
+            }
             0xF033 => {
                 self.memory[self.i_register as usize] = vx / 100;
                 self.memory[self.i_register as usize + 1] = (vx / 10) % 10;
@@ -324,11 +525,27 @@ impl Chip8 {
                 for i in 0..=x {
                     self.memory[self.i_register as usize + i] = self.registers[i];
                 }
+                if self.quirks.save_load_increments_i {
+                    self.i_register += x as u16 + 1;
+                }
             }
             0xF065 => {
                 for i in 0..=x {
                     self.registers[i] = self.memory[self.i_register as usize + i];
                 }
+                if self.quirks.save_load_increments_i {
+                    self.i_register += x as u16 + 1;
+                }
+            }
+            0xF075 => {
+                for i in 0..=x.min(7) {
+                    self.rpl_flags[i] = self.registers[i];
+                }
+            }
+            0xF085 => {
+                for i in 0..=x.min(7) {
+                    self.registers[i] = self.rpl_flags[i];
+                }
             }
             _ => panic!("Unknown opcode: {:#X}", opcode),
         }
@@ -0,0 +1,69 @@
+//! Decodes raw CHIP-8 opcodes into human-readable mnemonics for the debugger overlay.
+
+/// Decode a single opcode into a mnemonic, e.g. `0xD563 -> "DRW V5, V6, 3"`.
+pub fn disassemble(opcode: u16) -> String {
+    let nnn = opcode & 0x0FFF;
+    let n = opcode & 0x000F;
+    let x = ((opcode & 0x0F00) >> 8) as usize;
+    let y = ((opcode & 0x00F0) >> 4) as usize;
+    let kk = (opcode & 0x00FF) as u8;
+
+    match opcode & 0xF000 {
+        0x0000 => match opcode {
+            0x00E0 => "CLS".to_string(),
+            0x00EE => "RET".to_string(),
+            0x00FB => "SCR".to_string(),
+            0x00FC => "SCL".to_string(),
+            0x00FE => "LOW".to_string(),
+            0x00FF => "HIGH".to_string(),
+            _ if opcode & 0xFFF0 == 0x00C0 => format!("SCD {}", n),
+            _ => format!("SYS 0x{:03X}", nnn),
+        },
+        0x1000 => format!("JP 0x{:03X}", nnn),
+        0x2000 => format!("CALL 0x{:03X}", nnn),
+        0x3000 => format!("SE V{:X}, {}", x, kk),
+        0x4000 => format!("SNE V{:X}, {}", x, kk),
+        0x5000 => format!("SE V{:X}, V{:X}", x, y),
+        0x6000 => format!("LD V{:X}, {}", x, kk),
+        0x7000 => format!("ADD V{:X}, {}", x, kk),
+        0x8000 => match opcode & 0xF00F {
+            0x8000 => format!("LD V{:X}, V{:X}", x, y),
+            0x8001 => format!("OR V{:X}, V{:X}", x, y),
+            0x8002 => format!("AND V{:X}, V{:X}", x, y),
+            0x8003 => format!("XOR V{:X}, V{:X}", x, y),
+            0x8004 => format!("ADD V{:X}, V{:X}", x, y),
+            0x8005 => format!("SUB V{:X}, V{:X}", x, y),
+            0x8006 => format!("SHR V{:X}, V{:X}", x, y),
+            0x8007 => format!("SUBN V{:X}, V{:X}", x, y),
+            0x800E => format!("SHL V{:X}, V{:X}", x, y),
+            _ => format!("DATA 0x{:04X}", opcode),
+        },
+        0x9000 => format!("SNE V{:X}, V{:X}", x, y),
+        0xA000 => format!("LD I, 0x{:03X}", nnn),
+        0xB000 => format!("JP V0, 0x{:03X}", nnn),
+        0xC000 => format!("RND V{:X}, {}", x, kk),
+        0xD000 => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        0xE000 => match opcode & 0xF0FF {
+            0xE09E => format!("SKP V{:X}", x),
+            0xE0A1 => format!("SKNP V{:X}", x),
+            _ => format!("DATA 0x{:04X}", opcode),
+        },
+        0xF000 => match opcode & 0xF0FF {
+            0xF002 => "LD PATTERN, [I]".to_string(),
+            0xF007 => format!("LD V{:X}, DT", x),
+            0xF00A => format!("LD V{:X}, K", x),
+            0xF015 => format!("LD DT, V{:X}", x),
+            0xF018 => format!("LD ST, V{:X}", x),
+            0xF01E => format!("ADD I, V{:X}", x),
+            0xF029 => format!("LD F, V{:X}", x),
+            0xF033 => format!("LD B, V{:X}", x),
+            0xF03A => format!("LD PITCH, V{:X}", x),
+            0xF055 => format!("LD [I], V{:X}", x),
+            0xF065 => format!("LD V{:X}, [I]", x),
+            0xF075 => format!("LD R, V{:X}", x),
+            0xF085 => format!("LD V{:X}, R", x),
+            _ => format!("DATA 0x{:04X}", opcode),
+        },
+        _ => format!("DATA 0x{:04X}", opcode),
+    }
+}
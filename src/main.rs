@@ -1,12 +1,16 @@
 mod audio;
 mod chip8;
+mod disasm;
 
-use audio::SineWave;
+use audio::{PatternSource, PatternState};
 use bevy::prelude::*;
-use chip8::Chip8;
+use chip8::{Chip8, CompatProfile};
 use clap::Parser;
-use rodio::{OutputStream, Source, SpatialSink};
+use rodio::{OutputStream, Source};
+use std::collections::VecDeque;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -14,6 +18,22 @@ struct Args {
     /// Path to the ROM file
     #[arg(short, long)]
     rom: PathBuf,
+
+    /// Pause execution at this PC address (hex, e.g. 2F0) and wait for single-stepping
+    #[arg(long, value_parser = parse_hex_u16)]
+    breakpoint: Option<u16>,
+
+    /// Compatibility profile for ambiguous opcodes (defaults to this emulator's legacy behavior)
+    #[arg(long)]
+    compat: Option<CompatProfile>,
+
+    /// CPU clock rate in Hz
+    #[arg(long, default_value_t = 500)]
+    freq1: u64,
+}
+
+fn parse_hex_u16(s: &str) -> Result<u16, String> {
+    u16::from_str_radix(s.trim_start_matches("0x"), 16).map_err(|e| e.to_string())
 }
 
 fn main() {
@@ -23,23 +43,147 @@ fn main() {
     let mut chip8 = Chip8::default();
 
     chip8.load_from_file(&args.rom);
+    if let Some(compat) = args.compat {
+        chip8.quirks = compat.into();
+    }
+
+    let debugger = Debugger {
+        paused: false,
+        breakpoint: args.breakpoint,
+    };
 
     App::new()
         .add_plugins(DefaultPlugins)
         .insert_resource(chip8)
+        .insert_resource(debugger)
+        .insert_resource(Timing::new(args.freq1))
+        .insert_resource(RomPath(args.rom))
+        .init_resource::<SnapshotHistory>()
         .add_systems(Startup, setup)
         .add_systems(Startup, setup_display)
         .add_systems(Startup, setup_sound)
         .add_systems(Update, (update_keys, draw_display))
         .add_systems(Update, update_sound)
+        .add_systems(Update, (save_snapshot, rewind_snapshot))
+        .add_systems(Update, (save_state_file, load_state_file))
+        .add_systems(Update, (toggle_debugger, draw_disassembly))
         .add_systems(FixedUpdate, (run_chip8, update_chip8_timers))
         .add_systems(FixedUpdate, draw_registers)
         .run();
 }
 
+/// Path of the loaded ROM, used to derive the on-disk save-state path.
+#[derive(Resource)]
+struct RomPath(PathBuf);
+
+impl RomPath {
+    fn state_path(&self) -> PathBuf {
+        self.0.with_extension("state")
+    }
+}
+
+/// Gates emulation and lets the user single-step through opcodes.
+#[derive(Resource, Default)]
+struct Debugger {
+    paused: bool,
+    breakpoint: Option<u16>,
+}
+
+/// F1 toggles debug pause on and off.
+fn toggle_debugger(keyboard_input: Res<ButtonInput<KeyCode>>, mut debugger: ResMut<Debugger>) {
+    if keyboard_input.just_pressed(KeyCode::F1) {
+        debugger.paused = !debugger.paused;
+    }
+}
+
+fn check_breakpoint(chip8: &Chip8, debugger: &mut Debugger) -> bool {
+    if debugger.breakpoint == Some(chip8.pc) {
+        debugger.paused = true;
+        true
+    } else {
+        false
+    }
+}
+
+/// How many rewind snapshots to keep around before the oldest is dropped.
+const MAX_SNAPSHOTS: usize = 300;
+
+/// CPU cycles between automatic rewind captures, so F9 always has something recent to
+/// rewind to even when the user didn't know a mistake was coming and never pressed F5.
+const AUTO_SNAPSHOT_INTERVAL_CYCLES: u64 = 200;
+
+#[derive(Resource, Default)]
+struct SnapshotHistory {
+    snapshots: VecDeque<Vec<u8>>,
+    cycles_since_snapshot: u64,
+}
+
+fn push_snapshot(chip8: &Chip8, history: &mut SnapshotHistory) {
+    if history.snapshots.len() == MAX_SNAPSHOTS {
+        history.snapshots.pop_front();
+    }
+    history.snapshots.push_back(chip8.save_state());
+    history.cycles_since_snapshot = 0;
+}
+
+/// F5 forces an immediate snapshot into the rewind ring buffer, on top of the
+/// automatic periodic capture driven from `run_chip8`.
+fn save_snapshot(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    chip8: Res<Chip8>,
+    mut history: ResMut<SnapshotHistory>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F5) {
+        push_snapshot(&chip8, &mut history);
+    }
+}
+
+/// F9 rewinds to the most recent snapshot, whether it was captured automatically or
+/// with F5.
+fn rewind_snapshot(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut chip8: ResMut<Chip8>,
+    mut history: ResMut<SnapshotHistory>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F9) {
+        if let Some(state) = history.snapshots.pop_back() {
+            chip8.load_state(&state);
+        }
+    }
+}
+
+/// F6 writes a save state to disk alongside the ROM, at the ROM's path with its
+/// extension replaced by `.state`.
+fn save_state_file(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    chip8: Res<Chip8>,
+    rom_path: Res<RomPath>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F6) {
+        chip8.save_state_to_file(&rom_path.state_path());
+    }
+}
+
+/// F7 restores the save state written by F6.
+fn load_state_file(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut chip8: ResMut<Chip8>,
+    rom_path: Res<RomPath>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F7) {
+        chip8.load_state_from_file(&rom_path.state_path());
+    }
+}
+
 #[derive(Component)]
 struct PcText;
 
+#[derive(Component)]
+struct DisasmText;
+
+/// How many instructions to show in the disassembly window, centered on `pc`.
+const DISASM_WINDOW: usize = 16;
+
 fn setup(mut commands: Commands) {
     commands.spawn(Camera2d::default());
     commands.spawn((
@@ -50,25 +194,134 @@ fn setup(mut commands: Commands) {
         },
         PcText,
     ));
+    commands.spawn((
+        Text::new(""),
+        TextFont {
+            font_size: 14.0,
+            ..default()
+        },
+        Node {
+            position_type: PositionType::Absolute,
+            right: Val::Px(10.0),
+            top: Val::Px(10.0),
+            ..default()
+        },
+        DisasmText,
+    ));
+}
+
+/// Audio sample rate that every clock in the emulator is ultimately phase-locked to.
+const AUDIO_SAMPLE_RATE: u64 = 44100;
+
+/// Spreads exactly `freq1` events across `freq2` ticks using integer Bresenham timing,
+/// so the event count stays exact over any number of ticks instead of slowly drifting
+/// the way repeated floating-point accumulation does.
+struct Sampler {
+    q0: u64,
+    r0: u64,
+    freq2: u64,
+    cnt: u64,
 }
 
-/// Update CHIP-8 emu with 500Hz
-fn run_chip8(mut chip8: ResMut<Chip8>, time: Res<Time>, mut accumulator: Local<f32>) {
-    *accumulator += time.delta_secs();
-    let cycle_time = 1.0 / 500.0;
-    while *accumulator >= cycle_time {
-        chip8.execute_opcode();
-        *accumulator -= cycle_time;
+impl Sampler {
+    fn new(freq1: u64, freq2: u64) -> Self {
+        Self {
+            q0: freq1 / freq2,
+            r0: freq1 % freq2,
+            freq2,
+            cnt: 0,
+        }
     }
+
+    /// How many events to run on this tick.
+    fn steps(&mut self) -> u64 {
+        let mut steps = self.q0;
+        self.cnt += self.r0;
+        if self.cnt >= self.freq2 {
+            self.cnt -= self.freq2;
+            steps += 1;
+        }
+        steps
+    }
+}
+
+/// Holds the CPU and timer samplers, both driven off the same real audio-sample clock
+/// (see [`AudioClock`]), one Bresenham step per sample actually consumed by the mixer.
+#[derive(Resource)]
+struct Timing {
+    cpu: Sampler,
+    timers: Sampler,
 }
 
-/// Timers update systems 60Hz
-fn update_chip8_timers(mut chip8: ResMut<Chip8>, time: Res<Time>, mut accumulator: Local<f32>) {
-    *accumulator += time.delta_secs();
-    let timer_interval = 1.0 / 60.0;
-    while *accumulator >= timer_interval {
-        chip8.update_timers();
-        *accumulator -= timer_interval;
+impl Timing {
+    fn new(cpu_hz: u64) -> Self {
+        Self {
+            cpu: Sampler::new(cpu_hz, AUDIO_SAMPLE_RATE),
+            timers: Sampler::new(60, AUDIO_SAMPLE_RATE),
+        }
+    }
+}
+
+/// Shared count of samples `PatternSource` has produced on the rodio mixer thread. CPU
+/// and timer stepping read this instead of Bevy's wall clock, so they're genuinely
+/// paced by the audio hardware rather than just labelled with its sample rate.
+#[derive(Resource, Clone)]
+struct AudioClock(Arc<AtomicU64>);
+
+/// Runs the CPU at `freq1` Hz, phase-locked to the audio clock: each sample the mixer
+/// thread has consumed since the last call advances `Timing` by one tick. When the
+/// debugger is paused, execution only advances one opcode at a time on spacebar.
+fn run_chip8(
+    mut chip8: ResMut<Chip8>,
+    mut timing: ResMut<Timing>,
+    mut debugger: ResMut<Debugger>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut history: ResMut<SnapshotHistory>,
+    audio_clock: Res<AudioClock>,
+    mut last_sample: Local<u64>,
+) {
+    let sample = audio_clock.0.load(Ordering::Relaxed);
+    let elapsed = sample.saturating_sub(*last_sample);
+    *last_sample = sample;
+
+    if debugger.paused {
+        if keyboard_input.just_pressed(KeyCode::Space) {
+            chip8.execute_opcode();
+            check_breakpoint(&chip8, &mut debugger);
+        }
+        return;
+    }
+
+    for _ in 0..elapsed {
+        for _ in 0..timing.cpu.steps() {
+            chip8.execute_opcode();
+            history.cycles_since_snapshot += 1;
+            if history.cycles_since_snapshot >= AUTO_SNAPSHOT_INTERVAL_CYCLES {
+                push_snapshot(&chip8, &mut history);
+            }
+            if check_breakpoint(&chip8, &mut debugger) {
+                return;
+            }
+        }
+    }
+}
+
+/// Updates the 60Hz delay/sound timers, phase-locked to the audio clock the same way
+/// `run_chip8` is.
+fn update_chip8_timers(
+    mut chip8: ResMut<Chip8>,
+    mut timing: ResMut<Timing>,
+    audio_clock: Res<AudioClock>,
+    mut last_sample: Local<u64>,
+) {
+    let sample = audio_clock.0.load(Ordering::Relaxed);
+    let elapsed = sample.saturating_sub(*last_sample);
+    *last_sample = sample;
+
+    for _ in 0..elapsed {
+        for _ in 0..timing.timers.steps() {
+            chip8.update_timers();
+        }
     }
 }
 
@@ -104,10 +357,12 @@ fn update_keys(keyboard_input: Res<ButtonInput<KeyCode>>, mut chip8: ResMut<Chip
 
 // Dysplay systems
 //
-const DISPLAY_WIDTH: usize = 64;
-const DISPLAY_HEIGHT: usize = 32;
+// The pixel grid is always spawned at SUPER-CHIP's hi-res 128x64 size; in lo-res mode
+// draw_display upscales each logical pixel 2x so the window stays the same physical size.
+const DISPLAY_WIDTH: usize = 128;
+const DISPLAY_HEIGHT: usize = 64;
 
-const PIXEL_SIZE: f32 = 10.0;
+const PIXEL_SIZE: f32 = 5.0;
 
 const COLOR_ON: Color = Color::srgb(0.0, 1.0, 0.0);
 const COLOR_OFF: Color = Color::srgb(0.0, 0.0, 0.0);
@@ -135,8 +390,10 @@ fn setup_display(mut commands: Commands) {
 }
 
 fn draw_display(chip8: Res<Chip8>, mut query: Query<(&Chip8Pixel, &mut Sprite)>) {
+    let scale = DISPLAY_WIDTH / chip8.display_width();
     for (px, mut sprite) in query.iter_mut() {
-        sprite.color = if chip8.display[px.y][px.x] > 0 {
+        let (logical_x, logical_y) = (px.x / scale, px.y / scale);
+        sprite.color = if chip8.display[logical_y][logical_x] > 0 {
             COLOR_ON
         } else {
             COLOR_OFF
@@ -150,8 +407,32 @@ fn draw_registers(chip8: Res<Chip8>, mut text: Single<&mut Text, With<PcText>>)
     text.0 = format!("PC: {:04X}\nOP: {:04X}", pc, op);
 }
 
+/// Renders a scrolling window of disassembled instructions centered on `pc`, with the
+/// current line marked by `>`.
+fn draw_disassembly(chip8: Res<Chip8>, mut text: Single<&mut Text, With<DisasmText>>) {
+    let half = (DISASM_WINDOW as u16 / 2) * 2;
+    let start = chip8.pc.saturating_sub(half);
+
+    let mut lines = Vec::with_capacity(DISASM_WINDOW);
+    for i in 0..DISASM_WINDOW as u16 {
+        let addr = start + i * 2;
+        if addr as usize + 1 >= chip8.memory.len() {
+            break;
+        }
+        let opcode = (chip8.memory[addr as usize] as u16) << 8 | chip8.memory[addr as usize + 1] as u16;
+        let marker = if addr == chip8.pc { ">" } else { " " };
+        lines.push(format!("{} {:04X}: {}", marker, addr, disasm::disassemble(opcode)));
+    }
+    text.0 = lines.join("\n");
+}
+
 /// Audio systems
 
+/// Shared handle to the XO-CHIP pattern state, cloned into the `PatternSource` running
+/// on the mixer thread and updated each frame from the `Chip8` resource.
+#[derive(Resource, Clone)]
+struct AudioPatternHandle(Arc<Mutex<PatternState>>);
+
 fn setup_sound(world: &mut World) {
     let (_stream, stream_handle) = OutputStream::try_default().unwrap();
     let sink = rodio::SpatialSink::try_new(
@@ -161,18 +442,28 @@ fn setup_sound(world: &mut World) {
         [0.1, 0.0, 0.0],
     )
     .unwrap();
-    let source = SineWave::new(220.0).amplify(0.2).convert_samples::<f32>();
+
+    let pattern_state = Arc::new(Mutex::new(PatternState::default()));
+    let samples_played = Arc::new(AtomicU64::new(0));
+    let source = PatternSource::new(pattern_state.clone(), samples_played.clone())
+        .amplify(0.2)
+        .convert_samples::<f32>();
     sink.append(source);
-    sink.pause();
+    // Keep the mixer thread pulling samples continuously: muting is handled via
+    // `PatternState::muted` instead of pausing the sink, so `AudioClock` keeps
+    // advancing at a steady real audio rate even while the CHIP-8 sound timer is 0.
+    sink.play();
 
+    world.insert_resource(AudioPatternHandle(pattern_state));
+    world.insert_resource(AudioClock(samples_played));
     world.insert_non_send_resource(sink);
     world.insert_non_send_resource(_stream);
 }
 
-fn update_sound(chip8: Res<Chip8>, sink: NonSend<SpatialSink>) {
-    if chip8.sound_timer > 0 {
-        sink.play();
-    } else {
-        sink.pause();
-    }
+fn update_sound(chip8: Res<Chip8>, pattern: Res<AudioPatternHandle>) {
+    let mut state = pattern.0.lock().unwrap();
+    state.pattern = chip8.pattern_buffer;
+    state.pitch = chip8.pitch;
+    state.loaded = chip8.pattern_loaded;
+    state.muted = chip8.sound_timer == 0;
 }